@@ -5,18 +5,31 @@
 #![deny(missing_docs)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use std::{collections::HashSet, io};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 use maelstrom::{Framework, Message, Node};
 use serde_json::{Map, Value};
 
-/// Handles broadcasting.
+/// How often each node gossips its pending values to its neighbors.
+const GOSSIP_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Handles broadcasting via periodic anti-entropy gossip rather than fanning each message out
+/// to every neighbor as it arrives; this bounds message volume and tolerates dropped packets,
+/// since a value missing from one round's `gossip` is simply included in the next.
 struct BroadcastNode {
     /// Which message IDs have been seen.
     seen: HashSet<u64>,
 
     /// The NodeIds of our neighbors.
     neighbors: Vec<String>,
+
+    /// Which message IDs each neighbor has confirmed receiving, via `gossip_ok`.
+    known_by: HashMap<String, HashSet<u64>>,
 }
 
 impl BroadcastNode {
@@ -25,47 +38,68 @@ impl BroadcastNode {
         BroadcastNode {
             seen: HashSet::new(),
             neighbors: Vec::new(),
+            known_by: HashMap::new(),
         }
     }
 
-    /// Broadcast a message to all peers.
-    fn broadcast(&mut self, framework: &mut Framework, msg: Message) -> io::Result<()> {
+    /// Record a message, returning whether it was new.
+    fn record(&mut self, message: u64) -> bool {
+        self.seen.insert(message)
+    }
+
+    /// The message IDs `neighbor` has not yet confirmed.
+    fn pending_for(&self, neighbor: &str) -> Vec<u64> {
+        let known = self.known_by.get(neighbor);
+        self.seen
+            .iter()
+            .copied()
+            .filter(|id| known.is_none_or(|known| !known.contains(id)))
+            .collect()
+    }
+
+    /// Merge message IDs received from a neighbor's gossip.
+    fn merge(&mut self, ids: &[u64]) {
+        self.seen.extend(ids);
+    }
+
+    /// Record that `neighbor` has confirmed receiving `ids`.
+    fn ack(&mut self, neighbor: &str, ids: &[u64]) {
+        self.known_by
+            .entry(neighbor.to_owned())
+            .or_default()
+            .extend(ids);
+    }
+
+    /// Handle a client `broadcast` request.
+    fn broadcast(&mut self, framework: &Framework, msg: Message) {
         let message = msg
             .body
             .get("message")
             .and_then(Value::as_u64)
             .expect("`message` is required and must be a u64");
-        if self.seen.insert(message) {
-            let mut body = Map::new();
-            body.insert("type".to_owned(), "broadcast_ok".into());
-            framework.reply(msg, body)?;
-            for neighbor in &self.neighbors {
-                let mut body = Map::new();
-                body.insert("type".to_owned(), "broadcast".into());
-                body.insert("message".to_owned(), message.into());
-                framework.send(neighbor.to_owned(), body)?;
-            }
-        }
-        Ok(())
+        self.record(message);
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "broadcast_ok".into());
+        framework.reply(msg, body);
     }
 
     /// Reply with seen message IDs.
-    fn read(&mut self, framework: &mut Framework, msg: Message) -> io::Result<()> {
+    fn read(&mut self, framework: &Framework, msg: Message) {
         let mut body = Map::new();
         body.insert("type".to_owned(), "read_ok".into());
         body.insert("messages".to_owned(), self.seen.iter().copied().collect());
-        framework.reply(msg, body)
+        framework.reply(msg, body);
     }
 
     /// Set the topology of our neighbors.
-    fn topology(&mut self, framework: &mut Framework, msg: Message) -> io::Result<()> {
+    fn topology(&mut self, framework: &Framework, msg: Message) {
         self.neighbors = msg
             .body
             .get("topology")
             .expect("topology will exist")
             .as_object()
             .expect("topology will be an object")
-            .get(framework.node_id())
+            .get(&framework.node_id())
             .expect("node_id will exist in topology")
             .as_array()
             .expect("neighbors will be an array")
@@ -79,22 +113,136 @@ impl BroadcastNode {
             .collect();
         let mut body = Map::new();
         body.insert("type".to_owned(), "topology_ok".into());
-        framework.reply(msg, body)
+        framework.reply(msg, body);
+    }
+
+    /// Send every neighbor a batch of the values it hasn't confirmed yet.
+    fn gossip_tick(&self, framework: &Framework) {
+        for neighbor in &self.neighbors {
+            let pending = self.pending_for(neighbor);
+            if pending.is_empty() {
+                continue;
+            }
+            let mut body = Map::new();
+            body.insert("type".to_owned(), "gossip".into());
+            body.insert("messages".to_owned(), pending.into());
+            framework.send(neighbor.to_owned(), body);
+        }
+    }
+
+    /// Merge an incoming gossip batch and acknowledge it.
+    fn gossip(&mut self, framework: &Framework, msg: Message) {
+        let ids = gossip_messages(&msg);
+        self.merge(&ids);
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "gossip_ok".into());
+        body.insert("messages".to_owned(), ids.into());
+        framework.reply(msg, body);
+    }
+
+    /// Record that a neighbor has confirmed a gossip batch.
+    fn gossip_ok(&mut self, msg: Message) {
+        let ids = gossip_messages(&msg);
+        self.ack(msg.src(), &ids);
     }
 }
 
+/// Extract the `messages` field of a `gossip`/`gossip_ok` body as a list of message ids.
+fn gossip_messages(msg: &Message) -> Vec<u64> {
+    msg.body
+        .get("messages")
+        .and_then(Value::as_array)
+        .expect("`messages` is required and must be an array")
+        .iter()
+        .map(|value| value.as_u64().expect("message id will be a u64"))
+        .collect()
+}
+
 impl Node for BroadcastNode {
-    fn handle(&mut self, framework: &mut Framework, msg: Message) -> io::Result<()> {
+    fn handle(&mut self, framework: Arc<Framework>, msg: Message) {
         match msg.r#type() {
-            "broadcast" => self.broadcast(framework, msg),
-            "read" => self.read(framework, msg),
-            "topology" => self.topology(framework, msg),
-            _ => Ok(()),
+            "broadcast" => self.broadcast(&framework, msg),
+            "read" => self.read(&framework, msg),
+            "topology" => self.topology(&framework, msg),
+            "gossip" => self.gossip(&framework, msg),
+            "gossip_ok" => self.gossip_ok(msg),
+            "gossip_tick" => self.gossip_tick(&framework),
+            _ => {}
         }
     }
 }
 
 fn main() {
     let node = BroadcastNode::new();
-    Framework::run(node).unwrap();
+    Framework::run(
+        node,
+        Some(Box::new(|framework: Arc<Framework>| {
+            thread::spawn(move || loop {
+                thread::sleep(GOSSIP_INTERVAL);
+                let node_id = framework.node_id();
+                let mut body = Map::new();
+                body.insert("type".to_owned(), "gossip_tick".into());
+                framework.inject(Message::new(node_id.clone(), node_id, body));
+            });
+        })),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exchange each node's pending gossip with its neighbors, letting `drop` simulate a lossy
+    /// link by vetoing individual `from -> to` sends for this round.
+    fn gossip_round(nodes: &mut HashMap<String, BroadcastNode>, drop: impl Fn(&str, &str) -> bool) {
+        let ids: Vec<String> = nodes.keys().cloned().collect();
+        let mut batches = Vec::new();
+        for from in &ids {
+            let neighbors = nodes[from].neighbors.clone();
+            for to in &neighbors {
+                if drop(from, to) {
+                    continue;
+                }
+                let pending = nodes[from].pending_for(to);
+                if !pending.is_empty() {
+                    batches.push((from.clone(), to.clone(), pending));
+                }
+            }
+        }
+        for (from, to, ids) in batches {
+            nodes.get_mut(&to).unwrap().merge(&ids);
+            nodes.get_mut(&from).unwrap().ack(&to, &ids);
+        }
+    }
+
+    #[test]
+    fn converges_despite_dropped_gossip() {
+        let names = ["n0", "n1", "n2"];
+        let mut nodes: HashMap<String, BroadcastNode> = names
+            .iter()
+            .map(|name| (name.to_string(), BroadcastNode::new()))
+            .collect();
+        for name in names {
+            nodes.get_mut(name).unwrap().neighbors = names
+                .iter()
+                .filter(|other| **other != name)
+                .map(|other| other.to_string())
+                .collect();
+        }
+
+        nodes.get_mut("n0").unwrap().record(1);
+        nodes.get_mut("n1").unwrap().record(2);
+
+        // The first round drops every send, simulating a lossy link; later rounds should still
+        // converge because pending values are recomputed (and so resent) each tick.
+        gossip_round(&mut nodes, |_, _| true);
+        for _ in 0..5 {
+            gossip_round(&mut nodes, |_, _| false);
+        }
+
+        let expected: HashSet<u64> = [1, 2].into_iter().collect();
+        for name in names {
+            assert_eq!(nodes[name].seen, expected);
+        }
+    }
 }
@@ -30,5 +30,5 @@ impl Node for EchoNode {
 }
 
 fn main() {
-    Framework::run(EchoNode).unwrap();
+    Framework::run(EchoNode, None);
 }
@@ -0,0 +1,231 @@
+//! # Grow-Only Counter
+//!
+//! The fourth Gossip Glomers challenge.
+
+#![deny(missing_docs)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use std::{io, sync::Arc, time::Duration};
+
+use maelstrom::{kv::KvStore, Framework, Message, Node};
+use serde_json::{Map, Value};
+
+/// How long to wait for a `seq-kv` RPC before giving up.
+const KV_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The grow-only counter. Each node keeps its own contribution in `seq-kv` under its node id,
+/// bumping it with a CAS retry loop; `read` sums every node's contribution.
+struct CounterNode {
+    /// The ids of every node in the cluster, known once `init` arrives.
+    node_ids: Vec<String>,
+}
+
+impl CounterNode {
+    /// Create a [`CounterNode`].
+    fn new() -> CounterNode {
+        CounterNode {
+            node_ids: Vec::new(),
+        }
+    }
+
+    /// Read `node_id`'s current contribution, treating a missing key as zero.
+    fn read_contribution(framework: &Framework, node_id: &str) -> io::Result<i64> {
+        match framework.kv_read_sync(KvStore::SeqKv, node_id.to_owned(), KV_TIMEOUT) {
+            Ok(msg) => Ok(msg
+                .body
+                .get("value")
+                .and_then(Value::as_i64)
+                .expect("`value` is always an integer")),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Add `delta` to this node's contribution via a CAS retry loop.
+    fn add(&mut self, framework: &Framework, msg: Message) -> io::Result<()> {
+        let delta = msg
+            .body
+            .get("delta")
+            .and_then(Value::as_i64)
+            .expect("`delta` is required and must be an integer");
+        let key = framework.node_id();
+        loop {
+            let current = Self::read_contribution(framework, &key)?;
+            match framework.kv_cas_sync(
+                KvStore::SeqKv,
+                key.clone(),
+                current,
+                current + delta,
+                true,
+                KV_TIMEOUT,
+            ) {
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "add_ok".into());
+        framework.reply(msg, body);
+        Ok(())
+    }
+
+    /// Sum every node's contribution.
+    fn read(&mut self, framework: &Framework, msg: Message) -> io::Result<()> {
+        let mut total = 0i64;
+        for node_id in &self.node_ids {
+            total += Self::read_contribution(framework, node_id)?;
+        }
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "read_ok".into());
+        body.insert("value".to_owned(), total.into());
+        framework.reply(msg, body);
+        Ok(())
+    }
+}
+
+impl Node for CounterNode {
+    fn init(&mut self, msg: &Message) {
+        self.node_ids = msg
+            .body
+            .get("node_ids")
+            .expect("node_ids will exist")
+            .as_array()
+            .expect("node_ids will be an array")
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .expect("node id will be a string")
+                    .to_owned()
+            })
+            .collect();
+    }
+
+    fn handle(&mut self, framework: Arc<Framework>, msg: Message) {
+        let result = match msg.r#type() {
+            "add" => self.add(&framework, msg),
+            "read" => self.read(&framework, msg),
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            eprintln!("gg-counter: kv rpc failed: {err}");
+        }
+    }
+}
+
+fn main() {
+    Framework::run(CounterNode::new(), None);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crossbeam::channel;
+
+    use super::*;
+
+    /// Drive a [`CounterNode`] through `init`, `add`, then `read`, playing the part of the
+    /// `seq-kv` service by hand so the CAS retry loop is exercised end to end through the real
+    /// dispatch loop (not just unit-tested in isolation).
+    #[test]
+    fn add_then_read_end_to_end() {
+        let (stdin_tx, stdin_rx) = channel::unbounded();
+        let (stdout_tx, stdout_rx) = channel::unbounded();
+        let injector = stdin_tx.clone();
+
+        thread::spawn(move || {
+            Framework::run_with_io(CounterNode::new(), None, stdin_rx, injector, stdout_tx);
+        });
+
+        let mut init_body = Map::new();
+        init_body.insert("type".to_owned(), "init".into());
+        init_body.insert("msg_id".to_owned(), 1.into());
+        init_body.insert("node_id".to_owned(), "n0".into());
+        init_body.insert("node_ids".to_owned(), vec![Value::from("n0")].into());
+        stdin_tx
+            .send(Message::new("c0".to_owned(), "n0".to_owned(), init_body))
+            .unwrap();
+        stdout_rx.recv_timeout(KV_TIMEOUT).unwrap(); // init_ok
+
+        let mut add_body = Map::new();
+        add_body.insert("type".to_owned(), "add".into());
+        add_body.insert("msg_id".to_owned(), 2.into());
+        add_body.insert("delta".to_owned(), 5.into());
+        stdin_tx
+            .send(Message::new("c0".to_owned(), "n0".to_owned(), add_body))
+            .unwrap();
+
+        // The CAS loop starts by reading the current value; answer as if the key is unset.
+        let read_req = stdout_rx.recv_timeout(KV_TIMEOUT).unwrap();
+        assert_eq!(read_req.r#type(), "read");
+        reply_error(&stdin_tx, &read_req, 20, "key does not exist");
+
+        // It should then CAS 0 -> 5, creating the key.
+        let cas_req = stdout_rx.recv_timeout(KV_TIMEOUT).unwrap();
+        assert_eq!(cas_req.r#type(), "cas");
+        assert_eq!(cas_req.body.get("from").and_then(Value::as_i64), Some(0));
+        assert_eq!(cas_req.body.get("to").and_then(Value::as_i64), Some(5));
+        reply_ok(&stdin_tx, &cas_req, "cas_ok");
+
+        let add_ok = stdout_rx.recv_timeout(KV_TIMEOUT).unwrap();
+        assert_eq!(add_ok.r#type(), "add_ok");
+
+        let mut read_body = Map::new();
+        read_body.insert("type".to_owned(), "read".into());
+        read_body.insert("msg_id".to_owned(), 3.into());
+        stdin_tx
+            .send(Message::new("c0".to_owned(), "n0".to_owned(), read_body))
+            .unwrap();
+
+        let sum_req = stdout_rx.recv_timeout(KV_TIMEOUT).unwrap();
+        assert_eq!(sum_req.r#type(), "read");
+        reply_value(&stdin_tx, &sum_req, 5);
+
+        let read_ok = stdout_rx.recv_timeout(KV_TIMEOUT).unwrap();
+        assert_eq!(read_ok.r#type(), "read_ok");
+        assert_eq!(read_ok.body.get("value").and_then(Value::as_i64), Some(5));
+    }
+
+    /// Reply to `req` with a Maelstrom `error` body, as `seq-kv` would.
+    fn reply_error(stdin_tx: &channel::Sender<Message>, req: &Message, code: u64, text: &str) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "error".into());
+        body.insert("in_reply_to".to_owned(), msg_id(req).into());
+        body.insert("code".to_owned(), code.into());
+        body.insert("text".to_owned(), text.into());
+        stdin_tx
+            .send(Message::new("seq-kv".to_owned(), "n0".to_owned(), body))
+            .unwrap();
+    }
+
+    /// Reply to `req` with the given `type` and no other fields, as `seq-kv` would for `cas_ok`.
+    fn reply_ok(stdin_tx: &channel::Sender<Message>, req: &Message, r#type: &str) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), r#type.into());
+        body.insert("in_reply_to".to_owned(), msg_id(req).into());
+        stdin_tx
+            .send(Message::new("seq-kv".to_owned(), "n0".to_owned(), body))
+            .unwrap();
+    }
+
+    /// Reply to `req` with a `read_ok` carrying `value`, as `seq-kv` would.
+    fn reply_value(stdin_tx: &channel::Sender<Message>, req: &Message, value: i64) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "read_ok".into());
+        body.insert("in_reply_to".to_owned(), msg_id(req).into());
+        body.insert("value".to_owned(), value.into());
+        stdin_tx
+            .send(Message::new("seq-kv".to_owned(), "n0".to_owned(), body))
+            .unwrap();
+    }
+
+    /// Extract the `msg_id` of an outgoing request so a reply can target it.
+    fn msg_id(msg: &Message) -> u64 {
+        msg.body
+            .get("msg_id")
+            .and_then(Value::as_u64)
+            .expect("`msg_id` is always set on outgoing rpcs")
+    }
+}
@@ -0,0 +1,131 @@
+//! Client for Maelstrom's built-in key-value store services (`seq-kv`, `lin-kv`, `lww-kv`).
+
+use std::{io, time::Duration};
+
+use serde_json::{Map, Value};
+
+use crate::{Callback, Framework, Message};
+
+/// One of Maelstrom's built-in key-value store services, addressed by its well-known node id.
+pub enum KvStore {
+    /// The sequentially-consistent store (`seq-kv`).
+    SeqKv,
+
+    /// The linearizable store (`lin-kv`).
+    LinKv,
+
+    /// The last-write-wins store (`lww-kv`).
+    LwwKv,
+}
+
+impl KvStore {
+    /// The node id Maelstrom routes this store's RPCs to.
+    fn dest(&self) -> &'static str {
+        match self {
+            KvStore::SeqKv => "seq-kv",
+            KvStore::LinKv => "lin-kv",
+            KvStore::LwwKv => "lww-kv",
+        }
+    }
+}
+
+impl Framework {
+    /// Read `key` from `store`, running `callback` with the response.
+    pub fn kv_read(&self, store: KvStore, key: impl Into<Value>, callback: Box<Callback>) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "read".into());
+        body.insert("key".to_owned(), key.into());
+        self.rpc(store.dest().to_owned(), body, callback);
+    }
+
+    /// Write `value` to `key` in `store`, running `callback` with the response.
+    pub fn kv_write(
+        &self,
+        store: KvStore,
+        key: impl Into<Value>,
+        value: impl Into<Value>,
+        callback: Box<Callback>,
+    ) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "write".into());
+        body.insert("key".to_owned(), key.into());
+        body.insert("value".to_owned(), value.into());
+        self.rpc(store.dest().to_owned(), body, callback);
+    }
+
+    /// Compare-and-swap `key` in `store` from `from` to `to`.
+    ///
+    /// The reply can come back as an `error` with code 20 (`key-does-not-exist`) or 22
+    /// (`precondition-failed`) instead of the usual `cas_ok`; both are handed to `callback`
+    /// unchanged so callers can drive their own read-modify-write retry loop rather than us
+    /// panicking on their behalf.
+    pub fn kv_cas(
+        &self,
+        store: KvStore,
+        key: impl Into<Value>,
+        from: impl Into<Value>,
+        to: impl Into<Value>,
+        create_if_missing: bool,
+        callback: Box<Callback>,
+    ) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "cas".into());
+        body.insert("key".to_owned(), key.into());
+        body.insert("from".to_owned(), from.into());
+        body.insert("to".to_owned(), to.into());
+        body.insert("create_if_missing".to_owned(), create_if_missing.into());
+        self.rpc(store.dest().to_owned(), body, callback);
+    }
+
+    /// Like [`kv_read`](Self::kv_read), but block for the response.
+    pub fn kv_read_sync(
+        &self,
+        store: KvStore,
+        key: impl Into<Value>,
+        timeout: Duration,
+    ) -> io::Result<Message> {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "read".into());
+        body.insert("key".to_owned(), key.into());
+        self.rpc_sync(store.dest().to_owned(), body, timeout)
+    }
+
+    /// Like [`kv_write`](Self::kv_write), but block for the response.
+    pub fn kv_write_sync(
+        &self,
+        store: KvStore,
+        key: impl Into<Value>,
+        value: impl Into<Value>,
+        timeout: Duration,
+    ) -> io::Result<Message> {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "write".into());
+        body.insert("key".to_owned(), key.into());
+        body.insert("value".to_owned(), value.into());
+        self.rpc_sync(store.dest().to_owned(), body, timeout)
+    }
+
+    /// Like [`kv_cas`](Self::kv_cas), but block for the response.
+    ///
+    /// A `key-does-not-exist` or `precondition-failed` reply comes back as an
+    /// [`io::ErrorKind::NotFound`] or [`io::ErrorKind::AlreadyExists`] error respectively (see
+    /// [`rpc_sync`](Framework::rpc_sync)), so callers can drive a CAS retry loop by matching on
+    /// `err.kind()`.
+    pub fn kv_cas_sync(
+        &self,
+        store: KvStore,
+        key: impl Into<Value>,
+        from: impl Into<Value>,
+        to: impl Into<Value>,
+        create_if_missing: bool,
+        timeout: Duration,
+    ) -> io::Result<Message> {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "cas".into());
+        body.insert("key".to_owned(), key.into());
+        body.insert("from".to_owned(), from.into());
+        body.insert("to".to_owned(), to.into());
+        body.insert("create_if_missing".to_owned(), create_if_missing.into());
+        self.rpc_sync(store.dest().to_owned(), body, timeout)
+    }
+}
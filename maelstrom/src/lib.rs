@@ -16,9 +16,12 @@ use std::{
     time::Duration,
 };
 
-use crossbeam::channel::{self, Sender};
+use crossbeam::channel::{self, Receiver, Sender};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+pub mod kv;
 
 /// A message handler than we can register by calling [`rpc()`].
 type Callback = dyn Fn(Message) + Send + Sync + 'static;
@@ -46,6 +49,9 @@ pub struct Framework {
 
     /// Callback functions registered by sending a message with [`rpc()`].
     callbacks: Mutex<HashMap<u64, Box<Callback>>>,
+
+    /// Feeds [`inject()`](Framework::inject)ed messages back into the node's own handler loop.
+    injector: Sender<Message>,
 }
 
 impl Framework {
@@ -82,18 +88,69 @@ impl Framework {
     }
 
     /// Send a message to a node expecting a response, registering a callback to be run when we
-    /// receive a response.
-    pub fn rpc(&self, to: String, mut body: Map<String, Value>, callback: Box<Callback>) {
+    /// receive a response. Returns the `msg_id` the callback was registered under.
+    pub fn rpc(&self, to: String, mut body: Map<String, Value>, callback: Box<Callback>) -> u64 {
         let msg_id = self.produce_msg_id();
         body.insert("msg_id".to_owned(), msg_id.into());
         self.send(to, body);
         self.callbacks.lock().unwrap().insert(msg_id, callback);
+        msg_id
+    }
+
+    /// Send a message to a node and block for up to `timeout` waiting for the response.
+    ///
+    /// On timeout, the pending callback is removed and an [`io::ErrorKind::TimedOut`] error is
+    /// returned instead.
+    pub fn rpc_sync(
+        &self,
+        to: String,
+        body: Map<String, Value>,
+        timeout: Duration,
+    ) -> io::Result<Message> {
+        let (tx, rx) = channel::bounded(1);
+        let msg_id = self.rpc(
+            to,
+            body,
+            Box::new(move |msg| {
+                let _ = tx.send(msg);
+            }),
+        );
+        let msg = rx.recv_timeout(timeout).map_err(|_| {
+            self.callbacks.lock().unwrap().remove(&msg_id);
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "rpc_sync timed out waiting for a response",
+            )
+        })?;
+        if msg.r#type() == "error" {
+            let code = msg.body.get("code").and_then(Value::as_u64).unwrap_or(0);
+            let text = msg
+                .body
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or("maelstrom error reply");
+            return Err(io::Error::new(error_kind(code), text.to_owned()));
+        }
+        Ok(msg)
+    }
+
+    /// Reply to `msg` with a Maelstrom `error` body.
+    pub fn reply_error(&self, msg: Message, code: ErrorCode, text: impl Into<String>) {
+        let mut body = Map::new();
+        body.insert("type".to_owned(), "error".into());
+        body.insert(
+            "code".to_owned(),
+            serde_json::to_value(code).expect("ErrorCode always serializes"),
+        );
+        body.insert("text".to_owned(), text.into().into());
+        self.reply(msg, body)
     }
 
     /// Get the current NodeId
     ///
     /// # Panics
-    ///     - If the node ID has not already been set.
+    ///
+    /// If the node ID has not already been set.
     pub fn node_id(&self) -> String {
         self.id.lock().unwrap().as_deref().unwrap().to_owned()
     }
@@ -107,27 +164,44 @@ impl Framework {
         self.send(msg.src, body)
     }
 
-    /// Create a new framework.
-    fn new(stdout: Sender<Message>) -> Framework {
+    /// Create a new framework. `injector` feeds the same queue the stdin reader thread writes
+    /// to, so messages pushed through [`inject()`](Framework::inject) are dispatched exactly
+    /// like ones that arrived over stdin.
+    fn new(stdout: Sender<Message>, injector: Sender<Message>) -> Framework {
         Framework {
             id: Mutex::default(),
             next_msg_id: 1.into(),
             callbacks: Mutex::default(),
             stdout,
+            injector,
         }
     }
 
+    /// Push a message into our own handler thread as though it had arrived over stdin.
+    ///
+    /// This is the backdoor background work (periodic gossip, timers, ...) uses to get back
+    /// onto the node's handler thread instead of racing it directly.
+    pub fn inject(&self, msg: Message) {
+        self.injector.send(msg).unwrap();
+    }
+
     /// Run the node, accepting and producing messages.
-    pub fn run(mut node: impl Node) {
+    ///
+    /// `on_init` runs once, right after the `init` message has been handled and the node id is
+    /// known; it's given the [`Framework`] so it can stash a clone and spawn background work
+    /// (e.g. a timer thread driving periodic gossip via [`inject()`](Framework::inject)).
+    pub fn run(node: impl Node + Send + 'static, on_init: Option<Box<dyn FnOnce(Arc<Framework>) + Send>>) {
         let (stdin_tx, stdin_rx) = channel::unbounded();
         let (stdout_tx, stdout_rx) = channel::unbounded();
-        let framework = Arc::new(Framework::new(stdout_tx));
-        thread::spawn(move || {
-            let stdin = io::stdin().lock();
-            for msg in stdin.lines() {
-                let msg: Message =
-                    serde_json::from_str(&msg.unwrap()).expect("we know we won't get invalid data");
-                stdin_tx.send(msg).unwrap();
+        thread::spawn({
+            let stdin_tx = stdin_tx.clone();
+            move || {
+                let stdin = io::stdin().lock();
+                for msg in stdin.lines() {
+                    let msg: Message = serde_json::from_str(&msg.unwrap())
+                        .expect("we know we won't get invalid data");
+                    stdin_tx.send(msg).unwrap();
+                }
             }
         });
         thread::spawn(move || {
@@ -143,18 +217,54 @@ impl Framework {
                 stdout.write_all(b"\n").unwrap();
             }
         });
-        for msg in stdin_rx {
-            match (msg.r#type(), msg.in_reply_to()) {
-                ("init", _) => {
-                    node.init(&msg);
-                    framework.init(msg);
+        Framework::run_with_io(node, on_init, stdin_rx, stdin_tx, stdout_tx);
+    }
+
+    /// Core dispatch loop behind [`run()`](Framework::run), parameterized over its message
+    /// source/sink so it can be driven by real stdio or, in tests, by a harness that never
+    /// touches the process (e.g. to play the part of a Maelstrom service like `seq-kv`).
+    ///
+    /// `Node::handle` runs on its own dedicated thread, separate from the thread below that
+    /// drains `stdin_rx` and fires registered [`rpc()`](Framework::rpc) callbacks. If the two
+    /// shared a thread, a handler blocked in [`rpc_sync()`](Framework::rpc_sync) would also be
+    /// the only thread able to deliver the reply it's waiting on, deadlocking until the call
+    /// times out.
+    pub fn run_with_io(
+        mut node: impl Node + Send + 'static,
+        on_init: Option<Box<dyn FnOnce(Arc<Framework>) + Send>>,
+        stdin_rx: Receiver<Message>,
+        injector: Sender<Message>,
+        stdout_tx: Sender<Message>,
+    ) {
+        let mut on_init = on_init;
+        let (handle_tx, handle_rx) = channel::unbounded::<Message>();
+        let framework = Arc::new(Framework::new(stdout_tx, injector));
+
+        thread::spawn({
+            let framework = Arc::clone(&framework);
+            move || {
+                for msg in handle_rx {
+                    if msg.r#type() == "init" {
+                        node.init(&msg);
+                        framework.init(msg);
+                        if let Some(on_init) = on_init.take() {
+                            on_init(Arc::clone(&framework));
+                        }
+                    } else {
+                        node.handle(Arc::clone(&framework), msg);
+                    }
                 }
-                (_, Some(msg_id)) => {
+            }
+        });
+
+        for msg in stdin_rx {
+            match msg.in_reply_to() {
+                Some(msg_id) => {
                     if let Some(handler) = framework.callbacks.lock().unwrap().remove(&msg_id) {
                         handler(msg);
                     }
                 }
-                _ => node.handle(Arc::clone(&framework), msg),
+                None => handle_tx.send(msg).unwrap(),
             }
         }
     }
@@ -181,6 +291,49 @@ impl Framework {
     }
 }
 
+/// Standard Maelstrom error codes carried by `error` message bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u32)]
+pub enum ErrorCode {
+    /// The request timed out.
+    Timeout = 0,
+    /// The target node does not exist.
+    NodeNotFound = 1,
+    /// The requested operation is not supported by this node.
+    NotSupported = 10,
+    /// The operation is temporarily unavailable; retrying may succeed.
+    TemporarilyUnavailable = 11,
+    /// The request was malformed.
+    MalformedRequest = 12,
+    /// The node crashed while handling the request.
+    Crash = 13,
+    /// The request was aborted.
+    Abort = 14,
+    /// The requested key does not exist.
+    KeyDoesNotExist = 20,
+    /// The requested key already exists.
+    KeyAlreadyExists = 21,
+    /// A `cas` precondition was not met.
+    PreconditionFailed = 22,
+    /// A transaction conflicted with another transaction.
+    TxnConflict = 30,
+}
+
+/// Map a Maelstrom error `code` to the closest matching [`io::ErrorKind`], so [`rpc_sync`]
+/// callers can match on it without depending on the `text` field.
+///
+/// [`rpc_sync`]: Framework::rpc_sync
+fn error_kind(code: u64) -> io::ErrorKind {
+    match code {
+        c if c == ErrorCode::Timeout as u64 => io::ErrorKind::TimedOut,
+        c if c == ErrorCode::KeyDoesNotExist as u64 => io::ErrorKind::NotFound,
+        c if c == ErrorCode::KeyAlreadyExists as u64 || c == ErrorCode::PreconditionFailed as u64 => {
+            io::ErrorKind::AlreadyExists
+        }
+        _ => io::ErrorKind::Other,
+    }
+}
+
 /// A message from another [`Node`].
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Message {
@@ -195,6 +348,16 @@ pub struct Message {
 }
 
 impl Message {
+    /// Build a message, e.g. one to hand to [`Framework::inject()`].
+    pub fn new(src: String, dest: String, body: Map<String, Value>) -> Message {
+        Message { src, dest, body }
+    }
+
+    /// Return the NodeId of the sender.
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
     /// Return the `type` field (nonoptional).
     pub fn r#type(&self) -> &str {
         self.body
@@ -218,3 +381,73 @@ impl Message {
             .map(|value| value.as_u64().expect("`in_reply_to` is always u64"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// On `"ping"`, blocks in `rpc_sync` talking to `"peer"` and reports what came back.
+    struct PingNode {
+        /// Where the result of the `rpc_sync` call is reported.
+        result_tx: mpsc::Sender<io::Result<Message>>,
+    }
+
+    impl Node for PingNode {
+        fn handle(&mut self, framework: Arc<Framework>, msg: Message) {
+            if msg.r#type() == "ping" {
+                let mut body = Map::new();
+                body.insert("type".to_owned(), "ping_req".into());
+                let result = framework.rpc_sync("peer".to_owned(), body, Duration::from_secs(1));
+                self.result_tx.send(result).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn rpc_sync_round_trips_through_the_real_dispatch_loop() {
+        let (stdin_tx, stdin_rx) = channel::unbounded();
+        let (stdout_tx, stdout_rx) = channel::unbounded();
+        let (result_tx, result_rx) = mpsc::channel();
+        let injector = stdin_tx.clone();
+
+        thread::spawn(move || {
+            Framework::run_with_io(PingNode { result_tx }, None, stdin_rx, injector, stdout_tx);
+        });
+
+        let mut init_body = Map::new();
+        init_body.insert("type".to_owned(), "init".into());
+        init_body.insert("msg_id".to_owned(), 1.into());
+        init_body.insert("node_id".to_owned(), "n0".into());
+        stdin_tx
+            .send(Message::new("c0".to_owned(), "n0".to_owned(), init_body))
+            .unwrap();
+        stdout_rx.recv_timeout(Duration::from_secs(1)).unwrap(); // init_ok
+
+        let mut ping_body = Map::new();
+        ping_body.insert("type".to_owned(), "ping".into());
+        stdin_tx
+            .send(Message::new("c0".to_owned(), "n0".to_owned(), ping_body))
+            .unwrap();
+
+        // Observe the outgoing RPC so we know which msg_id to reply to.
+        let outgoing = stdout_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(outgoing.r#type(), "ping_req");
+        let msg_id = outgoing
+            .body
+            .get("msg_id")
+            .and_then(Value::as_u64)
+            .unwrap();
+
+        let mut reply_body = Map::new();
+        reply_body.insert("type".to_owned(), "ping_req_ok".into());
+        reply_body.insert("in_reply_to".to_owned(), msg_id.into());
+        stdin_tx
+            .send(Message::new("peer".to_owned(), "n0".to_owned(), reply_body))
+            .unwrap();
+
+        let result = result_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(result.unwrap().r#type(), "ping_req_ok");
+    }
+}
@@ -33,5 +33,5 @@ impl Node for UniqueIdNode {
 }
 
 fn main() {
-    Framework::run(UniqueIdNode)
+    Framework::run(UniqueIdNode, None)
 }